@@ -2,29 +2,191 @@
 
 //! Rust kernel module that adds the /dev/exec misc device
 
-use core::pin::Pin;
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
 
 use kernel::{
     alloc::KBox,
     bindings, c_str,
     error::Error,
     fs::Kiocb,
-    iov::IovIterSource,
+    iov::{IovIterDest, IovIterSource},
     macros::{module, vtable},
     new_mutex, pr_info,
     prelude::*,
     sync::Mutex,
+    sysctl::Sysctl,
     try_pin_init,
-    uapi::{call_usermodehelper_exec, call_usermodehelper_setup, UMH_WAIT_PROC},
+    uaccess::UserSlice,
+    uapi::{
+        call_usermodehelper_exec, call_usermodehelper_setup, UserPtr, UMH_WAIT_EXEC, UMH_WAIT_PROC,
+    },
     ThisModule,
 };
 
+/// Default value of `kernel.devexec.max_bytes`: the largest write/exec payload accepted when the
+/// admin hasn't overridden it.
+const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Runtime kill-switch backing `kernel.devexec.enabled`. Checked without taking the per-device
+/// mutex so a disabled module adds no locking overhead to unrelated devices.
+static DEVEXEC_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Runtime cap backing `kernel.devexec.max_bytes`.
+static DEVEXEC_MAX_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_MAX_BYTES);
+
+/// ioctl command: set the argv blob used for the execution triggered by `DEVEXEC_EXEC`.
+///
+/// `arg` must point to a NUL-separated, double-NUL-terminated byte blob.
+const DEVEXEC_SET_ARGV: u32 = 1;
+
+/// ioctl command: set the envp blob used for the execution triggered by `DEVEXEC_EXEC`.
+///
+/// `arg` must point to a NUL-separated, double-NUL-terminated byte blob.
+const DEVEXEC_SET_ENVP: u32 = 2;
+
+/// ioctl command: set the `call_usermodehelper_exec` wait mode.
+///
+/// `arg` must be one of `UMH_WAIT_EXEC` or `UMH_WAIT_PROC`. `UMH_NO_WAIT` is rejected: argv/envp
+/// and the strings they point into are freed as soon as `execute()` returns, racing the helper
+/// thread's asynchronous read of them.
+const DEVEXEC_SET_WAIT: u32 = 3;
+
+/// ioctl command: override the uid the spawned helper's credentials are dropped to.
+const DEVEXEC_SET_UID: u32 = 4;
+
+/// ioctl command: override the gid the spawned helper's credentials are dropped to.
+const DEVEXEC_SET_GID: u32 = 5;
+
+/// ioctl command: set the `DEVEXEC_FLAG_*` bits that opt into stricter pre-exec checks.
+const DEVEXEC_SET_FLAGS: u32 = 6;
+
+/// ioctl command: run the staged argv/envp/wait config against the buffer written so far. Under
+/// `UMH_WAIT_PROC` this populates the device's buffer with the helper's captured output plus its
+/// exit-status trailer; under `UMH_WAIT_EXEC` nothing is captured and the buffer is left empty.
+///
+/// Unlike `release`, the caller's fd is still open when this runs, so a subsequent `read()` on the
+/// same fd can observe the result.
+const DEVEXEC_EXEC: u32 = 7;
+
+/// Require [`F_SEAL_WRITE`]/[`F_SEAL_GROW`]/[`F_SEAL_SHRINK`] to apply successfully, aborting
+/// execution instead of merely warning when sealing fails.
+const DEVEXEC_FLAG_REQUIRE_SEAL: u32 = 1 << 0;
+
+/// Require a strict ELF header, rejecting `#!` shebang scripts that are otherwise accepted.
+const DEVEXEC_FLAG_REQUIRE_ELF: u32 = 1 << 1;
+
+/// All flag bits recognized by [`DEVEXEC_SET_FLAGS`].
+const DEVEXEC_FLAG_ALL: u32 = DEVEXEC_FLAG_REQUIRE_SEAL | DEVEXEC_FLAG_REQUIRE_ELF;
+
+/// Upper bound on the size of an argv/envp blob copied in via ioctl.
+const MAX_IOCTL_BLOB: usize = bindings::PAGE_SIZE;
+
+/// memfd seal flags (`include/uapi/linux/fcntl.h`), applied to the program image before exec.
+const F_SEAL_SHRINK: u32 = 0x0002;
+const F_SEAL_GROW: u32 = 0x0004;
+const F_SEAL_WRITE: u32 = 0x0008;
+
+/// Returns whether `buffer` starts with a recognized executable magic: an ELF header, or (unless
+/// `require_elf` is set) a `#!` shebang line.
+fn has_executable_magic(buffer: &[u8], require_elf: bool) -> bool {
+    if buffer.starts_with(b"\x7fELF") {
+        return true;
+    }
+
+    !require_elf && buffer.starts_with(b"#!")
+}
+
+/// Copies a NUL-separated, double-NUL-terminated blob from userspace, bounded by
+/// [`MAX_IOCTL_BLOB`].
+fn copy_user_blob(arg: usize) -> Result<KVVec<u8>> {
+    let mut reader = UserSlice::new(arg as UserPtr, MAX_IOCTL_BLOB).reader();
+
+    let mut buf = KVVec::new();
+    let mut byte = [0u8; 1];
+    let mut saw_nul = false;
+    loop {
+        if buf.len() >= MAX_IOCTL_BLOB {
+            return Err(EFBIG);
+        }
+
+        reader.read_slice(&mut byte)?;
+        buf.push(byte[0], GFP_KERNEL)?;
+
+        if byte[0] == 0 {
+            if saw_nul {
+                break;
+            }
+            saw_nul = true;
+        } else {
+            saw_nul = false;
+        }
+    }
+
+    Ok(buf)
+}
+
+/// Drops the program memfd and both of our references to the output memfd (our own, plus the one
+/// that would otherwise have been handed off to the child), used on every early-return once both
+/// shmem files exist.
+fn abort_exec(mem_file: *mut bindings::file, out_file: *mut bindings::file) {
+    unsafe {
+        bindings::fput(mem_file);
+        bindings::fput(out_file);
+        bindings::fput(out_file);
+    }
+}
+
+/// Splits a NUL-separated, double-NUL-terminated blob into a NULL-terminated pointer array
+/// suitable for use as `argv`/`envp`, pointing into `buf`'s own storage.
+fn build_ptr_vec(buf: &mut KVVec<u8>) -> Result<KVVec<*mut u8>> {
+    let mut ptrs = KVVec::new();
+    let mut start = 0usize;
+
+    for i in 0..buf.len() {
+        if buf[i] != 0 {
+            continue;
+        }
+
+        if i == start {
+            // an empty string ends the sequence
+            break;
+        }
+
+        // SAFETY: `start` is within `buf`'s allocation
+        ptrs.push(unsafe { buf.as_mut_ptr().add(start) }, GFP_KERNEL)?;
+        start = i + 1;
+    }
+
+    ptrs.push(core::ptr::null_mut(), GFP_KERNEL)?;
+    Ok(ptrs)
+}
+
 module! {
    type: DevExecModule,
    name: "devexec",
    authors: ["Connor Sample"],
    description: "Rust kernel module that adds the /dev/exec misc device",
    license: "GPL",
+   params: {
+       mode: u16 {
+           default: 0o600,
+           permissions: 0o444,
+           description: "permission mode for the /dev/exec device node",
+       },
+       uid: u32 {
+           default: 0,
+           permissions: 0o644,
+           description: "uid the spawned helper's credentials are dropped to",
+       },
+       gid: u32 {
+           default: 0,
+           permissions: 0o644,
+           description: "gid the spawned helper's credentials are dropped to",
+       },
+   },
 }
 
 #[allow(improper_ctypes)]
@@ -38,6 +200,9 @@ unsafe extern "C" {
         // VM_NORESERVE supresses pre-accounting of the entire object size
         flags: c_ulong,
     ) -> *mut bindings::file;
+
+    /// apply F_SEAL_* flags to a shmem file returned by shmem_file_setup
+    unsafe fn shmem_add_seals(file: *mut bindings::file, seals: c_uint) -> c_int;
 }
 
 /// Module struct that registers the misc device
@@ -45,6 +210,10 @@ unsafe extern "C" {
 struct DevExecModule {
     #[pin]
     _miscdev: kernel::miscdevice::MiscDeviceRegistration<DevExecDevice>,
+    #[pin]
+    _sysctl_enabled: Sysctl<&'static AtomicBool>,
+    #[pin]
+    _sysctl_max_bytes: Sysctl<&'static AtomicU64>,
 }
 
 impl kernel::InPlaceModule for DevExecModule {
@@ -52,14 +221,28 @@ impl kernel::InPlaceModule for DevExecModule {
     fn init(_module: &'static ThisModule) -> impl PinInit<Self, Error> {
         pr_info!("devexec module (init)\n");
 
-        let options = kernel::miscdevice::MiscDeviceOptions {
-            // set the misc device to /dev/exec
-            name: kernel::c_str!("exec"),
-        };
+        // register /dev/exec as root-only (0600 by default) so that only privileged callers can
+        // trigger execution; the mode is configurable via the `mode` module parameter
+        let options = kernel::miscdevice::MiscDeviceOptions::new(kernel::c_str!("exec"))
+            .mode(*module_parameters::mode.read());
 
         // attempt to initialize the module
         try_pin_init!(Self {
             _miscdev <- kernel::miscdevice::MiscDeviceRegistration::register(options),
+            // kernel.devexec.enabled: runtime kill-switch for the whole execution path
+            _sysctl_enabled <- Sysctl::register(
+                c_str!("kernel/devexec"),
+                c_str!("enabled"),
+                &DEVEXEC_ENABLED,
+                0o644,
+            ),
+            // kernel.devexec.max_bytes: upper bound on the write/exec payload size
+            _sysctl_max_bytes <- Sysctl::register(
+                c_str!("kernel/devexec"),
+                c_str!("max_bytes"),
+                &DEVEXEC_MAX_BYTES,
+                0o644,
+            ),
         })
     }
 }
@@ -72,30 +255,116 @@ impl PinnedDrop for DevExecModule {
     }
 }
 
-/// Device state: holds a byte buffer written by userspace
+/// Execution configuration staged via ioctl before `DEVEXEC_EXEC` is issued. Reset to its default
+/// (including `read_pos`) every time `DevExecDevice::execute` finds a non-empty buffer to run, so
+/// each exec cycle starts from a clean slate and a lingering fd must re-stage argv/envp before
+/// triggering another one. An exec rejected as disabled or with an empty buffer keeps the config.
+struct ExecConfig {
+    /// argv blob; falls back to `/proc/self/fd/3` as `argv[0]` when unset
+    argv: Option<KVVec<u8>>,
+    /// envp blob; falls back to an empty environment when unset
+    envp: Option<KVVec<u8>>,
+    /// one of `UMH_WAIT_EXEC`, `UMH_WAIT_PROC` (`UMH_NO_WAIT` is rejected by `DEVEXEC_SET_WAIT`)
+    wait: u32,
+    /// uid the helper's credentials are dropped to; defaults to the `uid` module parameter
+    uid: u32,
+    /// gid the helper's credentials are dropped to; defaults to the `gid` module parameter
+    gid: u32,
+    /// `DEVEXEC_FLAG_*` bits opting into stricter pre-exec checks
+    flags: u32,
+    /// byte offset into `data` consumed so far by `read_iter`, tracked here rather than via the
+    /// file's own position so it stays in lockstep with `data` being cleared/replaced each cycle
+    read_pos: usize,
+}
+
+impl Default for ExecConfig {
+    fn default() -> Self {
+        Self {
+            argv: None,
+            envp: None,
+            wait: UMH_WAIT_PROC,
+            uid: *module_parameters::uid.read(),
+            gid: *module_parameters::gid.read(),
+            flags: 0,
+            read_pos: 0,
+        }
+    }
+}
+
+/// Context passed through `subprocess_info::data`, consumed by `kmod_devexec_init`.
+struct ExecContext {
+    /// memfd holding the program image, installed onto fd 3 of the spawned helper
+    file: *mut bindings::file,
+    /// memfd that captures the helper's stdout and stderr, installed onto fds 1 and 2
+    out_file: *mut bindings::file,
+    /// uid the helper's credentials are dropped to
+    uid: u32,
+    /// gid the helper's credentials are dropped to
+    gid: u32,
+}
+
+/// Device state: holds a byte buffer written by userspace, plus the execution config staged via
+/// ioctl
 #[pin_data(PinnedDrop)]
 struct DevExecDevice {
     #[pin]
     data: Mutex<KVVec<u8>>,
+    #[pin]
+    config: Mutex<ExecConfig>,
 }
 
 /// callback function passed to call_usermodehelper_setup.
 ///
 /// This is executed in the context of the child process setup. It installs the memfd file onto fd
-/// 3 of the spawned subprocess to that the helper can access the in memory file via
+/// 3 of the spawned subprocess so that the helper can access the in memory file via
 ///   /proc/self/fd/3
+/// installs the output-capturing memfd onto fds 1 and 2 so the helper's stdout/stderr land in it,
+/// and drops the child's credentials to the configured uid/gid with an empty capability set
+/// before the helper gets to exec the supplied image.
 #[no_mangle]
 unsafe extern "C" fn kmod_devexec_init(
     info: *mut kernel::uapi::subprocess_info,
-    _cred: *mut kernel::uapi::cred,
+    cred: *mut kernel::uapi::cred,
 ) -> c_int {
     const EXEC_FD: u32 = 3;
+    const STDOUT_FD: u32 = 1;
+    const STDERR_FD: u32 = 2;
+
+    // `subprocess_info->data` is used to pass our `ExecContext`.
+    // SAFETY: set to a `KBox::into_raw(ExecContext)` in `DevExecDevice::execute`
+    let ctx = unsafe { KBox::from_raw((*info).data as *mut ExecContext) };
+
+    // drop all capabilities and, if configured, the uid/gid, so the helper does not inherit the
+    // kernel thread's privileges; this runs before fd_install below so the new fds are never
+    // reachable under the kernel thread's original, more privileged credentials
+    unsafe {
+        (*cred).cap_effective = bindings::kernel_cap_t { val: 0 };
+        (*cred).cap_permitted = bindings::kernel_cap_t { val: 0 };
+
+        if ctx.uid != 0 {
+            let kuid = bindings::make_kuid(&mut bindings::init_user_ns, ctx.uid);
+            (*cred).uid = kuid;
+            (*cred).euid = kuid;
+            (*cred).suid = kuid;
+            (*cred).fsuid = kuid;
+        }
+
+        if ctx.gid != 0 {
+            let kgid = bindings::make_kgid(&mut bindings::init_user_ns, ctx.gid);
+            (*cred).gid = kgid;
+            (*cred).egid = kgid;
+            (*cred).sgid = kgid;
+            (*cred).fsgid = kgid;
+        }
+    }
 
-    // `subprocess_info->data` is used to pass our memfd pointer.
-    // We recast it to a `struct file *`
-    let file = unsafe { (*info).data as *mut kernel::uapi::file };
+    unsafe { kernel::uapi::fd_install(EXEC_FD, ctx.file) };
 
-    unsafe { kernel::uapi::fd_install(EXEC_FD, file) };
+    // fd_install consumes one reference per call; take an extra reference so the same memfd can
+    // be installed onto both fd 1 and fd 2
+    unsafe { bindings::get_file(ctx.out_file) };
+    unsafe { kernel::uapi::fd_install(STDOUT_FD, ctx.out_file) };
+    unsafe { kernel::uapi::fd_install(STDERR_FD, ctx.out_file) };
 
     0
 }
@@ -112,21 +381,86 @@ impl kernel::miscdevice::MiscDevice for DevExecDevice {
     ) -> Result<Pin<KBox<Self>>> {
         pr_info!("devexec: device opened\n");
 
-        // create a DevExecDevice with an empty buffer protected by a mutex
+        // create a DevExecDevice with an empty buffer and default execution config, both
+        // protected by a mutex
         KBox::try_pin_init(
             try_pin_init! {
                 DevExecDevice {
-                    data <- new_mutex!(KVVec::new())
+                    data <- new_mutex!(KVVec::new()),
+                    config <- new_mutex!(ExecConfig::default()),
                 }
             },
             GFP_KERNEL,
         )
     }
 
+    // Configures argv, envp, the wait mode, the drop-privilege uid/gid, or the DEVEXEC_FLAG_*
+    // strictness bits used by `DEVEXEC_EXEC`, or triggers execution itself
+    fn ioctl(device: Pin<&Self>, _file: &kernel::fs::File, cmd: u32, arg: usize) -> Result<isize> {
+        match cmd {
+            DEVEXEC_SET_ARGV => {
+                device.config.lock().argv = Some(copy_user_blob(arg)?);
+            }
+            DEVEXEC_SET_ENVP => {
+                device.config.lock().envp = Some(copy_user_blob(arg)?);
+            }
+            DEVEXEC_SET_WAIT => {
+                let wait = arg as u32;
+                // UMH_NO_WAIT is rejected: call_usermodehelper_setup stores the argv/envp
+                // pointers as-is and only reads them once the helper thread actually runs
+                // kernel_execve, which for NO_WAIT happens asynchronously with nothing to
+                // synchronize on; execute() would free both the moment it returns,
+                // racing the helper's read of now-freed memory. Only offer this once a real
+                // call_usermodehelper_setup cleanup callback defers that freeing instead.
+                if wait != UMH_WAIT_EXEC && wait != UMH_WAIT_PROC {
+                    return Err(EINVAL);
+                }
+                device.config.lock().wait = wait;
+            }
+            DEVEXEC_SET_UID => {
+                device.config.lock().uid = arg as u32;
+            }
+            DEVEXEC_SET_GID => {
+                device.config.lock().gid = arg as u32;
+            }
+            DEVEXEC_SET_FLAGS => {
+                let flags = arg as u32;
+                if flags & !DEVEXEC_FLAG_ALL != 0 {
+                    return Err(EINVAL);
+                }
+                device.config.lock().flags = flags;
+            }
+            DEVEXEC_EXEC => device.execute()?,
+            _ => return Err(ENOTTY),
+        }
+
+        Ok(0)
+    }
+
     // Copies the provided iterator into the device's buffer when userspace writes to /dev/exec
     fn write_iter(kiocb: Kiocb<'_, Self::Ptr>, iov: &mut IovIterSource<'_>) -> Result<usize> {
+        if !DEVEXEC_ENABLED.load(Ordering::Relaxed) {
+            return Err(EPERM);
+        }
+
         let file = kiocb.file();
         let mut guard = file.data.lock();
+
+        let max_bytes = DEVEXEC_MAX_BYTES.load(Ordering::Relaxed) as usize;
+        let remaining = max_bytes.saturating_sub(guard.len());
+        if remaining == 0 {
+            return Err(EFBIG);
+        }
+
+        // reject the write up front if it would grow the buffer past kernel.devexec.max_bytes,
+        // rather than allocating the whole payload and only checking afterwards; this bounds the
+        // allocation copy_from_iter_vec performs to at most `remaining` bytes and leaves
+        // previously-accepted writes untouched
+        if iov.len() > remaining {
+            pr_warn!("devexec: write would exceed kernel.devexec.max_bytes, rejecting\n");
+            return Err(EFBIG);
+        }
+
         // copy the iov iterator into the vector, allocating with GFP_KERNEL
         let len = iov.copy_from_iter_vec(&mut guard, GFP_KERNEL)?;
 
@@ -134,15 +468,75 @@ impl kernel::miscdevice::MiscDevice for DevExecDevice {
         Ok(len)
     }
 
-    // This is called when the device is closed. Execution is attempted
-    fn release(device: Self::Ptr, _file: &kernel::fs::File) {
-        pr_info!("devexec: device closed, attempting execution\n");
+    // Copies the device's buffer into the provided iterator. After a DEVEXEC_EXEC ioctl this
+    // serves up the helper's captured stdout/stderr followed by its exit-status trailer, so a
+    // process that keeps the fd open across the write/DEVEXEC_EXEC/read sequence gets a full
+    // request/response exec channel.
+    fn read_iter(kiocb: Kiocb<'_, Self::Ptr>, iov: &mut IovIterDest<'_>) -> Result<usize> {
+        let file = kiocb.file();
+        let mut guard = file.data.lock();
+
+        // tracked in the device's own config rather than via `kiocb.ki_pos()`, since `data` gets
+        // wholesale replaced by each DEVEXEC_EXEC rather than being seeked through like a regular
+        // file; a position left over from a previous exec cycle would otherwise make this look
+        // like EOF against a fresh, shorter buffer
+        let mut config = file.config.lock();
+        let pos = config.read_pos;
+        if pos >= guard.len() {
+            return Ok(0);
+        }
+
+        let len = iov.copy_to_iter_vec(&guard[pos..])?;
+        config.read_pos += len;
+
+        if config.read_pos >= guard.len() {
+            // fully drained: clear the buffer so a write() staging the next payload appends to an
+            // empty buffer instead of onto this cycle's now-consumed output
+            *guard = KVVec::new();
+            config.read_pos = 0;
+        }
+
+        pr_info!("devexec: read {} bytes\n", len);
+        Ok(len)
+    }
+
+    // This is called when the device is closed. Execution now happens on the DEVEXEC_EXEC ioctl
+    // while the fd is still open (see `DevExecDevice::execute`), since by the time `release` runs
+    // the struct file's refcount has already dropped to zero and no fd remains for a caller to
+    // read captured output back from; there is nothing left to do here but let the device drop.
+    fn release(_device: Self::Ptr, _file: &kernel::fs::File) {
+        pr_info!("devexec: device closed\n");
+    }
+}
+
+impl DevExecDevice {
+    // Builds the staged argv/envp/wait config into a usermode-helper invocation and, for
+    // UMH_WAIT_PROC, captures its stdout/stderr plus an exit-status trailer back into `data` for a
+    // subsequent read_iter. Triggered by the DEVEXEC_EXEC ioctl, so the caller's fd is still open
+    // when this runs.
+    fn execute(&self) -> Result {
+        if !DEVEXEC_ENABLED.load(Ordering::Relaxed) {
+            return Err(EPERM);
+        }
+
+        pr_info!("devexec: DEVEXEC_EXEC, attempting execution\n");
 
         // take the buffer out of the device
-        let buffer = core::mem::take(&mut *device.data.lock());
+        let buffer = core::mem::take(&mut *self.data.lock());
         if buffer.is_empty() {
             pr_warn!("devexec: buffer is empty, nothing to execute\n");
-            return;
+            return Err(ENOEXEC);
+        }
+
+        // take the execution config out of the device; needed up front since DEVEXEC_FLAG_*
+        // governs the magic check below
+        let mut config = core::mem::take(&mut *self.config.lock());
+
+        // reject anything that isn't a recognizable executable before doing anything else with
+        // it, to avoid spawning the usermode helper against arbitrary garbage
+        if !has_executable_magic(&buffer, config.flags & DEVEXEC_FLAG_REQUIRE_ELF != 0) {
+            pr_warn!("devexec: buffer has no recognized executable magic, refusing to execute\n");
+            return Err(ENOEXEC);
         }
 
         // name for the shmem file
@@ -153,7 +547,7 @@ impl kernel::miscdevice::MiscDevice for DevExecDevice {
         if unsafe { bindings::IS_ERR(mem_file_ptr as *const c_void) } {
             let err = unsafe { bindings::PTR_ERR(mem_file_ptr as *const c_void) };
             pr_err!("devexec: shmem_file_setup failed: {}\n", err);
-            return;
+            return Err(Error::from_errno(err as i32));
         }
 
         // write the buffer into the shmem file
@@ -171,20 +565,123 @@ impl kernel::miscdevice::MiscDevice for DevExecDevice {
             pr_err!("devexec: kernel_write to memfd failed: {}\n", ret);
             // drop the file reference explicitly if write failed
             unsafe { bindings::fput(mem_file_ptr) };
-            return;
+            return Err(Error::from_errno(ret as i32));
         }
 
         pr_info!("devexec: wrote {} bytes to memfd\n", ret);
 
+        // seal the image against further writes/growth/shrinkage so it can't be mutated between
+        // now and the helper actually exec'ing it
+        let seal_ret =
+            unsafe { shmem_add_seals(mem_file_ptr, F_SEAL_WRITE | F_SEAL_GROW | F_SEAL_SHRINK) };
+        if seal_ret < 0 {
+            if config.flags & DEVEXEC_FLAG_REQUIRE_SEAL != 0 {
+                pr_err!("devexec: failed to seal memfd: {}\n", seal_ret);
+                unsafe { bindings::fput(mem_file_ptr) };
+                return Err(Error::from_errno(seal_ret as i32));
+            }
+            pr_warn!(
+                "devexec: failed to seal memfd: {} (continuing, seal not required)\n",
+                seal_ret
+            );
+        }
+
+        // second shmem file that captures the helper's stdout/stderr; created empty and grown by
+        // the helper's own writes
+        let name = c_str!("kmod_devexec_out");
+        let out_file_ptr = unsafe { shmem_file_setup(name.as_ptr(), 0, 0) };
+
+        if unsafe { bindings::IS_ERR(out_file_ptr as *const c_void) } {
+            let err = unsafe { bindings::PTR_ERR(out_file_ptr as *const c_void) };
+            pr_err!("devexec: shmem_file_setup for output failed: {}\n", err);
+            unsafe { bindings::fput(mem_file_ptr) };
+            return Err(Error::from_errno(err as i32));
+        }
+
+        // take our own reference so we can still kernel_read the captured output after handing
+        // a reference off to the child via kmod_devexec_init
+        unsafe { bindings::get_file(out_file_ptr) };
+
         // point to our FD that will be created
         // SAFETY: this ends with a \0
-        let mut path_bytes = *b"/proc/self/fd/3\0";
-        let path_ptr: *mut u8 = path_bytes.as_mut_ptr() as *mut u8;
-        let mut argv = [path_ptr, core::ptr::null_mut()];
-        let mut envp = [core::ptr::null_mut()];
+        let mut fallback_path = *b"/proc/self/fd/3\0";
+
+        // build argv from the staged config, falling back to /proc/self/fd/3 when no explicit
+        // argv was set
+        let mut argv = match config.argv {
+            Some(ref mut blob) => match build_ptr_vec(blob) {
+                Ok(ptrs) => ptrs,
+                Err(e) => {
+                    pr_err!("devexec: invalid argv blob: {:?}\n", e);
+                    abort_exec(mem_file_ptr, out_file_ptr);
+                    return Err(e);
+                }
+            },
+            None => {
+                let mut ptrs = KVVec::new();
+                if ptrs.push(fallback_path.as_mut_ptr(), GFP_KERNEL).is_err()
+                    || ptrs.push(core::ptr::null_mut(), GFP_KERNEL).is_err()
+                {
+                    pr_err!("devexec: failed to allocate default argv\n");
+                    abort_exec(mem_file_ptr, out_file_ptr);
+                    return Err(ENOMEM);
+                }
+                ptrs
+            }
+        };
+
+        let mut envp = match config.envp {
+            Some(ref mut blob) => match build_ptr_vec(blob) {
+                Ok(ptrs) => ptrs,
+                Err(e) => {
+                    pr_err!("devexec: invalid envp blob: {:?}\n", e);
+                    abort_exec(mem_file_ptr, out_file_ptr);
+                    return Err(e);
+                }
+            },
+            None => {
+                let mut ptrs = KVVec::new();
+                if ptrs.push(core::ptr::null_mut(), GFP_KERNEL).is_err() {
+                    pr_err!("devexec: failed to allocate default envp\n");
+                    abort_exec(mem_file_ptr, out_file_ptr);
+                    return Err(ENOMEM);
+                }
+                ptrs
+            }
+        };
+
+        // a blob whose first entry is empty (e.g. a lone double-NUL) makes build_ptr_vec produce
+        // just the NULL terminator; reject that here instead of handing a NULL path to
+        // call_usermodehelper_setup
+        if argv.len() < 2 || argv[0].is_null() {
+            pr_err!("devexec: argv must contain at least one non-empty entry\n");
+            abort_exec(mem_file_ptr, out_file_ptr);
+            return Err(EINVAL);
+        }
+
+        let path_ptr = argv[0];
+
+        // bundle the memfd together with the drop-privilege uid/gid so kmod_devexec_init can act
+        // on both
+        let ctx = match KBox::new(
+            ExecContext {
+                file: mem_file_ptr,
+                out_file: out_file_ptr,
+                uid: config.uid,
+                gid: config.gid,
+            },
+            GFP_KERNEL,
+        ) {
+            Ok(ctx) => KBox::into_raw(ctx),
+            Err(_) => {
+                pr_err!("devexec: failed to allocate exec context\n");
+                abort_exec(mem_file_ptr, out_file_ptr);
+                return Err(ENOMEM);
+            }
+        };
 
-        // set up the subprocess, passing mem_file_ptr as `data` so that it can be installed into
-        // the child's fd table.
+        // set up the subprocess, passing `ctx` as `data` so that it can be installed into the
+        // child's fd table and used to drop its credentials.
         // using GFP_KERNEL for allocation
         let sub_info = unsafe {
             call_usermodehelper_setup(
@@ -194,20 +691,83 @@ impl kernel::miscdevice::MiscDevice for DevExecDevice {
                 bindings::GFP_KERNEL,
                 Some(kmod_devexec_init),
                 None,
-                mem_file_ptr as *mut c_void,
+                ctx as *mut c_void,
             )
         };
 
         if sub_info.is_null() {
             pr_err!("devexec: call_usermodehelper_setup failed\n");
-            // drop the file reference explicitly if write failed
-            unsafe { bindings::fput(mem_file_ptr) };
-            return;
+            // reclaim the context and drop both file references explicitly if setup failed,
+            // since kmod_devexec_init will never run to do so
+            unsafe { drop(KBox::from_raw(ctx)) };
+            abort_exec(mem_file_ptr, out_file_ptr);
+            return Err(EIO);
         }
 
-        // execute the userspace helper process that wait for it to finish
-        let ret = unsafe { call_usermodehelper_exec(sub_info, UMH_WAIT_PROC.try_into().unwrap()) };
+        // execute the userspace helper process, honoring the configured wait mode
+        let ret = unsafe { call_usermodehelper_exec(sub_info, config.wait.try_into().unwrap()) };
         pr_info!("devexec: usermode helper returned {}\n", ret);
+
+        if config.wait != UMH_WAIT_PROC {
+            // UMH_WAIT_EXEC: the helper has execve'd but may still be running, so we can't
+            // safely read its output yet; just drop our own reference and move on
+            unsafe { bindings::fput(out_file_ptr) };
+            return Ok(());
+        }
+
+        // the helper has exited; read back whatever it wrote to stdout/stderr, capped at
+        // kernel.devexec.max_bytes so a helper that floods stdout/stderr can't force the same
+        // unbounded kernel allocation write_iter already guards against
+        let max_bytes = DEVEXEC_MAX_BYTES.load(Ordering::Relaxed) as usize;
+        let mut output = KVVec::new();
+        let mut read_offset: i64 = 0;
+        loop {
+            if output.len() >= max_bytes {
+                pr_warn!("devexec: captured output hit kernel.devexec.max_bytes, truncating\n");
+                break;
+            }
+
+            let mut chunk = [0u8; 256];
+            let to_read = chunk.len().min(max_bytes - output.len());
+            let n = unsafe {
+                kernel::uapi::kernel_read(
+                    out_file_ptr.cast(),
+                    chunk.as_mut_ptr().cast(),
+                    to_read,
+                    &mut read_offset,
+                )
+            };
+
+            if n <= 0 {
+                if n < 0 {
+                    pr_err!("devexec: kernel_read of captured output failed: {}\n", n);
+                }
+                break;
+            }
+
+            for byte in &chunk[..n as usize] {
+                if output.push(*byte, GFP_KERNEL).is_err() {
+                    pr_err!("devexec: failed to grow output buffer\n");
+                    break;
+                }
+            }
+        }
+
+        unsafe { bindings::fput(out_file_ptr) };
+
+        pr_info!("devexec: captured {} bytes of output\n", output.len());
+
+        // trailer: the raw usermode-helper return value as 4 little-endian bytes, so a reader can
+        // tell the captured output apart from the exit status
+        for byte in ret.to_le_bytes() {
+            if output.push(byte, GFP_KERNEL).is_err() {
+                pr_err!("devexec: failed to append exit status trailer\n");
+                break;
+            }
+        }
+
+        *self.data.lock() = output;
+        Ok(())
     }
 }
 